@@ -0,0 +1,411 @@
+use anyhow::{bail, Result};
+use lzma_rs::lzma_decompress;
+
+/// LZMA custom-decompress GUID (the one `try_find_agesa` previously assumed
+/// for every GUID-defined section).
+pub const LZMA_GUID: [u8; 0x10] =
+    [0x98, 0x58, 0x4E, 0xEE, 0x14, 0x39, 0x59, 0x42, 0x9D, 0x6E, 0xDC, 0x7B, 0xD7, 0x94, 0x03, 0xCF];
+
+/// Tiano/EFI standard compression GUID.
+pub const TIANO_GUID: [u8; 0x10] =
+    [0xAD, 0x80, 0x12, 0xA3, 0x1E, 0x48, 0xB6, 0x41, 0x95, 0xE8, 0x12, 0x7F, 0x4C, 0x98, 0x47, 0x79];
+
+/// Brotli GUID.
+pub const BROTLI_GUID: [u8; 0x10] =
+    [0x50, 0x20, 0x53, 0x3D, 0xDA, 0x5C, 0xD0, 0x4F, 0x87, 0x9E, 0x0F, 0x7F, 0x63, 0x0D, 0x5A, 0xFB];
+
+/// Decompresses `body` according to the GUID-defined section's `guid`.
+///
+/// Returns `Ok(None)` for GUIDs we don't recognize so the caller can log and
+/// skip the section instead of failing the whole scan.
+pub fn decompress_section(guid: &[u8; 0x10], body: &[u8]) -> Result<Option<Vec<u8>>> {
+    match *guid {
+        LZMA_GUID => {
+            let mut enc_body = body;
+            let mut dec_body = Vec::new();
+            lzma_decompress(&mut enc_body, &mut dec_body).map_err(|e| anyhow::anyhow!("{e}"))?;
+            Ok(Some(dec_body))
+        },
+        TIANO_GUID => Ok(Some(tiano_decompress(body)?)),
+        BROTLI_GUID => {
+            let mut dec_body = Vec::new();
+            brotli::BrotliDecompress(&mut &body[..], &mut dec_body)
+                .map_err(|e| anyhow::anyhow!("{e}"))?;
+            Ok(Some(dec_body))
+        },
+        _ => Ok(None),
+    }
+}
+
+const BITBUFSIZ: u32 = 32;
+const THRESHOLD: usize = 3;
+
+const NC: usize = 0xff + 256 + 2 - THRESHOLD;
+const CBIT: u32 = 9;
+const MAXPBIT: u32 = 5;
+const TBIT: u32 = 5;
+const NT: usize = (CBIT + 3) as usize;
+const NPT: usize = if NT > (1 << MAXPBIT) - 1 { NT } else { (1 << MAXPBIT) - 1 };
+const NP: usize = (1usize << MAXPBIT) - 1;
+
+/// A slot that Tiano's two-level Huffman table build can point at: either a
+/// direct entry in the flat lookup table, or a node in the overflow tree
+/// (`left`/`right`) once the code is longer than the table's bit width.
+#[derive(Clone, Copy)]
+enum Slot {
+    Table(usize),
+    Left(usize),
+    Right(usize),
+}
+
+/// Port of the EFI "Tiano" standard-compression decoder (LZ77 + adaptive
+/// two-level Huffman), as described in the UEFI PI spec's firmware volume
+/// compression appendix.
+struct TianoDecompressor<'a> {
+    src: &'a [u8],
+    src_pos: usize,
+
+    dst: Vec<u8>,
+    dst_size: usize,
+
+    bit_count: u32,
+    bit_buf: u32,
+    sub_bit_buf: u32,
+
+    block_size: u32,
+
+    left: [u16; 2 * NC - 1],
+    right: [u16; 2 * NC - 1],
+    c_len: [u8; NC],
+    pt_len: [u8; NPT],
+    c_table: [u16; 4096],
+    pt_table: [u16; 256],
+}
+
+impl<'a> TianoDecompressor<'a> {
+    fn new(src: &'a [u8], dst_size: usize) -> Self {
+        let mut d = TianoDecompressor {
+            src,
+            src_pos: 0,
+            dst: Vec::with_capacity(dst_size),
+            dst_size,
+            bit_count: 0,
+            bit_buf: 0,
+            sub_bit_buf: 0,
+            block_size: 0,
+            left: [0; 2 * NC - 1],
+            right: [0; 2 * NC - 1],
+            c_len: [0; NC],
+            pt_len: [0; NPT],
+            c_table: [0; 4096],
+            pt_table: [0; 256],
+        };
+        d.fill_buf(BITBUFSIZ);
+        d
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        let b = self.src.get(self.src_pos).copied().unwrap_or(0);
+        self.src_pos += 1;
+        b
+    }
+
+    fn fill_buf(&mut self, mut num_of_bits: u32) {
+        self.bit_buf = self.bit_buf.wrapping_shl(num_of_bits);
+
+        while num_of_bits > self.bit_count {
+            self.bit_buf |= self.sub_bit_buf << (num_of_bits - self.bit_count);
+            self.sub_bit_buf = self.next_byte() as u32;
+            num_of_bits -= self.bit_count;
+            self.bit_count = 8;
+        }
+
+        self.bit_count -= num_of_bits;
+        self.bit_buf |= self.sub_bit_buf >> self.bit_count;
+    }
+
+    fn get_bits(&mut self, num_of_bits: u32) -> u32 {
+        let out = self.bit_buf >> (BITBUFSIZ - num_of_bits);
+        self.fill_buf(num_of_bits);
+        out
+    }
+
+    fn slot_get(&self, slot: Slot, table: &[u16]) -> u16 {
+        match slot {
+            Slot::Table(i) => table[i],
+            Slot::Left(i) => self.left[i],
+            Slot::Right(i) => self.right[i],
+        }
+    }
+
+    fn slot_set(&mut self, slot: Slot, table: &mut [u16], value: u16) {
+        match slot {
+            Slot::Table(i) => table[i] = value,
+            Slot::Left(i) => self.left[i] = value,
+            Slot::Right(i) => self.right[i] = value,
+        }
+    }
+
+    fn make_table(
+        &mut self,
+        num_of_char: usize,
+        bit_len: &[u8],
+        table_bits: u32,
+        table: &mut [u16],
+    ) -> Result<()> {
+        let mut count = [0u16; 17];
+        let mut weight = [0u16; 17];
+        let mut start = [0u16; 18];
+
+        for &len in &bit_len[..num_of_char] {
+            count[len as usize] += 1;
+        }
+
+        for i in 1..=16usize {
+            start[i + 1] = start[i].wrapping_add(count[i] << (16 - i));
+        }
+        if start[17] != 0 {
+            bail!("Tiano: bad Huffman table");
+        }
+
+        let ju_bits = 16 - table_bits;
+        for i in 1..=table_bits as usize {
+            start[i] >>= ju_bits;
+            weight[i] = 1u16 << (table_bits - i as u32);
+        }
+        for i in (table_bits as usize + 1)..=16 {
+            weight[i] = 1u16 << (16 - i);
+        }
+
+        let first_unused = (start[table_bits as usize + 1] >> ju_bits) as usize;
+        if first_unused != 0 {
+            let limit = 1usize << table_bits;
+            table.iter_mut().take(limit).skip(first_unused).for_each(|t| *t = 0);
+        }
+
+        let mut avail = num_of_char as u16;
+        let mask = 1u16 << (15 - table_bits);
+
+        for (char_, &len) in bit_len.iter().take(num_of_char).enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let len = len as usize;
+            let next_code = start[len] + weight[len];
+
+            if len <= table_bits as usize {
+                table
+                    .iter_mut()
+                    .take(next_code as usize)
+                    .skip(start[len] as usize)
+                    .for_each(|t| *t = char_ as u16);
+            } else {
+                let mut index3 = start[len];
+                let mut slot = Slot::Table((index3 >> ju_bits) as usize);
+                let mut remaining = len as u32 - table_bits;
+
+                while remaining != 0 {
+                    if self.slot_get(slot, table) == 0 {
+                        let node = avail as usize;
+                        self.left[node] = 0;
+                        self.right[node] = 0;
+                        self.slot_set(slot, table, avail);
+                        avail += 1;
+                    }
+                    let node = self.slot_get(slot, table) as usize;
+                    slot = if index3 & mask != 0 { Slot::Right(node) } else { Slot::Left(node) };
+                    index3 <<= 1;
+                    remaining -= 1;
+                }
+                self.slot_set(slot, table, char_ as u16);
+            }
+            start[len] = next_code;
+        }
+
+        Ok(())
+    }
+
+    fn read_pt_len(&mut self, num: usize, num_bits: u32, special: u16) -> Result<()> {
+        let number = self.get_bits(num_bits) as usize;
+
+        if number == 0 {
+            let char_code = self.get_bits(num_bits) as u16;
+            self.pt_table.fill(char_code);
+            self.pt_len[..num].fill(0);
+            return Ok(());
+        }
+
+        let mut index = 0;
+        while index < number {
+            let mut char_code = self.get_bits(3) as u16;
+            if char_code == 7 {
+                while self.get_bits(1) != 0 {
+                    char_code += 1;
+                }
+            }
+            self.pt_len[index] = char_code as u8;
+            index += 1;
+
+            if index as u16 == special {
+                let mut extra = self.get_bits(2);
+                while extra > 0 {
+                    self.pt_len[index] = 0;
+                    index += 1;
+                    extra -= 1;
+                }
+            }
+        }
+        while index < num {
+            self.pt_len[index] = 0;
+            index += 1;
+        }
+
+        let pt_len = self.pt_len;
+        let mut pt_table = self.pt_table;
+        self.make_table(num, &pt_len, 8, &mut pt_table)?;
+        self.pt_table = pt_table;
+        Ok(())
+    }
+
+    fn read_c_len(&mut self) -> Result<()> {
+        let number = self.get_bits(CBIT) as usize;
+
+        if number == 0 {
+            let char_code = self.get_bits(CBIT) as u8;
+            self.c_len.fill(0);
+            self.c_table.fill(char_code as u16);
+            return Ok(());
+        }
+
+        let mut index = 0;
+        while index < number {
+            let mut char_code = self.pt_table[(self.bit_buf >> (BITBUFSIZ - 8)) as usize];
+            if char_code as usize >= NT {
+                let mut mask = 1u32 << (BITBUFSIZ - 1 - 8);
+                loop {
+                    char_code =
+                        if self.bit_buf & mask != 0 { self.right[char_code as usize] } else { self.left[char_code as usize] };
+                    mask >>= 1;
+                    if (char_code as usize) < NT {
+                        break;
+                    }
+                }
+            }
+            self.fill_buf(self.pt_len[char_code as usize] as u32);
+
+            if char_code <= 2 {
+                let run = match char_code {
+                    0 => 1,
+                    1 => self.get_bits(4) + 3,
+                    _ => self.get_bits(CBIT) + 20,
+                };
+                for _ in 0..run {
+                    if index >= NC {
+                        break;
+                    }
+                    self.c_len[index] = 0;
+                    index += 1;
+                }
+            } else {
+                self.c_len[index] = (char_code - 2) as u8;
+                index += 1;
+            }
+        }
+        while index < NC {
+            self.c_len[index] = 0;
+            index += 1;
+        }
+
+        let c_len = self.c_len;
+        let mut c_table = self.c_table;
+        self.make_table(NC, &c_len, 12, &mut c_table)?;
+        self.c_table = c_table;
+        Ok(())
+    }
+
+    fn decode_c(&mut self) -> Result<u16> {
+        if self.block_size == 0 {
+            self.block_size = self.get_bits(16);
+            self.read_pt_len(NT, TBIT, 3)?;
+            self.read_c_len()?;
+            self.read_pt_len(NP, MAXPBIT, u16::MAX)?;
+        }
+        self.block_size -= 1;
+
+        let mut index = self.c_table[(self.bit_buf >> (BITBUFSIZ - 12)) as usize];
+        if index as usize >= NC {
+            let mut mask = 1u32 << (BITBUFSIZ - 1 - 12);
+            loop {
+                index = if self.bit_buf & mask != 0 { self.right[index as usize] } else { self.left[index as usize] };
+                mask >>= 1;
+                if (index as usize) < NC {
+                    break;
+                }
+            }
+        }
+        self.fill_buf(self.c_len[index as usize] as u32);
+
+        Ok(index)
+    }
+
+    fn decode_p(&mut self) -> u32 {
+        let mut val = self.pt_table[(self.bit_buf >> (BITBUFSIZ - 8)) as usize];
+        let max_np = (1u16 << MAXPBIT) - 1;
+        if val >= max_np {
+            let mut mask = 1u32 << (BITBUFSIZ - 1 - 8);
+            loop {
+                val = if self.bit_buf & mask != 0 { self.right[val as usize] } else { self.left[val as usize] };
+                mask >>= 1;
+                if val < max_np {
+                    break;
+                }
+            }
+        }
+        self.fill_buf(self.pt_len[val as usize] as u32);
+
+        if val > 1 {
+            (1u32 << (val - 1)) + self.get_bits(val as u32 - 1)
+        } else {
+            val as u32
+        }
+    }
+
+    fn decode(&mut self) -> Result<Vec<u8>> {
+        while self.dst.len() < self.dst_size {
+            let char_code = self.decode_c()?;
+
+            if char_code < 256 {
+                self.dst.push(char_code as u8);
+            } else {
+                let run = (char_code as usize) - (0xff + 1 - THRESHOLD);
+                let dist = self.decode_p() as usize + 1;
+                let start = self.dst.len().checked_sub(dist).ok_or_else(|| {
+                    anyhow::anyhow!("Tiano: back-reference points before start of output")
+                })?;
+
+                for i in 0..run {
+                    if self.dst.len() >= self.dst_size {
+                        break;
+                    }
+                    let byte = self.dst[start + i];
+                    self.dst.push(byte);
+                }
+            }
+        }
+
+        Ok(self.dst.clone())
+    }
+}
+
+/// Decompresses an EFI "Tiano" standard-compression section body. The
+/// section starts with an 8-byte little-endian `(compressed_size,
+/// original_size)` pair followed by the LZ77+Huffman bitstream.
+pub fn tiano_decompress(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 8 {
+        bail!("Tiano section too small for header");
+    }
+
+    let original_size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+
+    TianoDecompressor::new(&data[8..], original_size).decode()
+}
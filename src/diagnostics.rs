@@ -0,0 +1,98 @@
+use core::fmt;
+use std::fmt::Write as _;
+
+/// A parse error anchored to a byte offset in the source image, carrying
+/// enough to render a hex-dump window around the failure instead of just a
+/// flat message — the way assembler/VM toolchains attach source-span
+/// snippets to errors. Implements `std::error::Error` so it can be wrapped
+/// in an `anyhow::Error` like any other error and later recovered with
+/// `downcast_ref`.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub offset: usize,
+    pub span: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Diagnostic {
+    pub fn error(offset: usize, message: impl Into<String>) -> Self {
+        Diagnostic { offset, span: 1, severity: Severity::Error, message: message.into() }
+    }
+
+    /// Like `error`, but for failures a caller can recover from by skipping
+    /// just the offending entry (e.g. one malformed PSP entry header) rather
+    /// than losing the whole directory.
+    pub fn warning(offset: usize, message: impl Into<String>) -> Self {
+        Diagnostic { offset, span: 1, severity: Severity::Warning, message: message.into() }
+    }
+
+    /// Widens the diagnostic to cover `span` offending bytes starting at
+    /// `offset` (e.g. the 4 bytes of a bad directory signature) instead of
+    /// just the single anchor byte.
+    pub fn with_span(mut self, span: usize) -> Self {
+        self.span = span.max(1);
+        self
+    }
+
+    /// Renders a labeled hex-dump window `context` bytes either side of
+    /// `self.offset`, highlighting the offending `self.span` bytes with
+    /// `[ ]` markers so a reader can immediately tell whether they hit
+    /// padding, a truncated directory, or a genuinely new signature.
+    pub fn render(&self, data: &[u8], context: usize) -> String {
+        let start = self.offset.saturating_sub(context);
+        let end = (self.offset + self.span + context).min(data.len());
+
+        let label = match self.severity {
+            Severity::Error => "ERROR",
+            Severity::Warning => "WARNING",
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{:08X} [{}]: {}", self.offset, label, self.message);
+
+        let window = match data.get(start..end) {
+            Some(window) => window,
+            None => {
+                let _ = writeln!(out, "  <offset out of bounds, {} byte(s) of data>", data.len());
+                return out;
+            },
+        };
+
+        for (row_start, row) in
+            window.chunks(16).enumerate().map(|(i, row)| (start + i * 16, row))
+        {
+            let _ = write!(out, "  {:08X}  ", row_start);
+            for (i, byte) in row.iter().enumerate() {
+                let abs = row_start + i;
+                let highlighted = abs >= self.offset && abs < self.offset + self.span;
+                if highlighted {
+                    let _ = write!(out, "[{:02X}]", byte);
+                } else {
+                    let _ = write!(out, " {:02X} ", byte);
+                }
+            }
+            let _ = write!(out, " ");
+            for &byte in row {
+                out.push(if byte.is_ascii_graphic() { byte as char } else { '.' });
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X}: {}", self.offset, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -0,0 +1,46 @@
+use std::io::{Read, Seek};
+use std::mem::size_of;
+
+use anyhow::{Context, Result};
+use bytemuck::try_from_bytes;
+
+use crate::structs::{
+    BhdDirectoryEntry, ComboDirectoryEntry, ComboDirectoryHeader, DirectoryHeader,
+    EfiGuidDefinedSection, FirmwareEntryTable, PspDirectoryEntry, PspEntryHeader,
+};
+
+/// Parses `Self` from the current position of a `Read + Seek` stream, the
+/// streaming counterpart to the `bytemuck::try_from_bytes` + slice-indexing
+/// pattern the in-memory parsers used to rely on. Lets directory traversal
+/// operate on a `BufReader<File>` or an `mmap` view, seeking to
+/// `resolve_location` and reading one struct at a time, instead of requiring
+/// the whole image resident in RAM.
+pub trait FromReader: Sized {
+    fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self>;
+}
+
+/// Implements `FromReader` for a fixed-size `Pod` header/entry type: read
+/// exactly `size_of::<T>()` bytes and reinterpret them in place, mirroring
+/// what `try_from_bytes` does against an in-memory slice.
+macro_rules! impl_from_reader_pod {
+    ($ty:ty) => {
+        impl FromReader for $ty {
+            fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+                let mut buf = [0u8; size_of::<$ty>()];
+                reader.read_exact(&mut buf).context(concat!("Could not read ", stringify!($ty)))?;
+                try_from_bytes::<$ty>(&buf)
+                    .map(|parsed| *parsed)
+                    .context(concat!("Could not parse ", stringify!($ty)))
+            }
+        }
+    };
+}
+
+impl_from_reader_pod!(FirmwareEntryTable);
+impl_from_reader_pod!(DirectoryHeader);
+impl_from_reader_pod!(ComboDirectoryHeader);
+impl_from_reader_pod!(ComboDirectoryEntry);
+impl_from_reader_pod!(PspDirectoryEntry);
+impl_from_reader_pod!(BhdDirectoryEntry);
+impl_from_reader_pod!(PspEntryHeader);
+impl_from_reader_pod!(EfiGuidDefinedSection);
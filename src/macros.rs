@@ -3,41 +3,67 @@ macro_rules! make_dir {
     ($visibility:vis $name:ident, $header_type:ty, $entry_type:ty) => {
         #[allow(dead_code)]
         #[derive(Debug)]
-        $visibility struct $name<'a> {
+        $visibility struct $name {
             $visibility address: usize,
-            $visibility header: &'a $header_type,
-            $visibility entries: Vec<&'a $entry_type>,
+            $visibility header: $header_type,
+            $visibility entries: Vec<$entry_type>,
+            $visibility checksum: $crate::structs::Checksum,
         }
 
-        impl $name<'_> {
-            pub fn new(address: usize, data: &[u8]) -> anyhow::Result<$name> {
-                use anyhow::Context;
+        impl $name {
+            /// Seeks `reader` to `address` and reads the header, its
+            /// entries and the Fletcher-32 checksum covering them, one
+            /// fixed-size struct at a time, instead of requiring `address`
+            /// to already be resident in an in-memory slice.
+            pub fn new<R: std::io::Read + std::io::Seek>(
+                address: usize,
+                reader: &mut R,
+            ) -> anyhow::Result<$name> {
+                use std::io::{Read, Seek, SeekFrom};
+
+                use $crate::diagnostics::Diagnostic;
+                use $crate::io::FromReader;
 
                 const HEADER_SIZE: usize = size_of::<$header_type>();
                 const ENTRY_SIZE: usize = size_of::<$entry_type>();
 
-                let data = match data.get(address..) {
-                    None => anyhow::bail!(concat!("Could not fetch ", stringify!($name))),
-                    Some(data) => data,
-                };
+                reader
+                    .seek(SeekFrom::Start(address as u64))
+                    .map_err(|_| Diagnostic::error(address, concat!("Could not fetch ", stringify!($name))))?;
+
+                let header = <$header_type>::from_reader(reader)
+                    .map_err(|_| Diagnostic::error(address, concat!("Could not parse ", stringify!($name))))?;
+
+                let mut entries = Vec::with_capacity(header.entries as usize);
+                for _ in 0..header.entries {
+                    entries.push(<$entry_type>::from_reader(reader).map_err(|_| {
+                        Diagnostic::error(address, concat!("Could not parse ", stringify!($name), " entries"))
+                    })?);
+                }
+
+                // A directory whose `header.entries` runs past the end of the stream has
+                // already failed above with an error. Reading the checksummed body does not
+                // panic: it reads as much as is available, and a short read falls out as a
+                // mismatched (invalid) checksum.
+                let body_len = HEADER_SIZE + entries.len() * ENTRY_SIZE;
+                let mut body = vec![0u8; body_len.saturating_sub(0x08)];
+                let mut read = 0;
+                if reader.seek(SeekFrom::Start(address as u64 + 0x08)).is_ok() {
+                    while read < body.len() {
+                        match reader.read(&mut body[read..]) {
+                            Ok(0) => break,
+                            Ok(n) => read += n,
+                            Err(_) => break,
+                        }
+                    }
+                }
 
-                let header = match data.get(..HEADER_SIZE) {
-                    None => anyhow::bail!(concat!("Could not fetch ", stringify!($name))),
-                    Some(header) => header,
+                let checksum = $crate::structs::Checksum {
+                    expected: u32::from_le_bytes(header.checksum),
+                    computed: $crate::utils::fletcher32(&body[..read]),
                 };
 
-                try_from_bytes::<$header_type>(header)
-                    .and_then(|header| {
-                        // TODO!: error handling
-                        Ok($name {
-                            address,
-                            header,
-                            entries: data[HEADER_SIZE..][..header.entries as usize * ENTRY_SIZE]
-                                .chunks_exact(ENTRY_SIZE)
-                                .filter_map(|chunk| try_from_bytes::<$entry_type>(chunk).ok())
-                                .collect::<Vec<_>>(),
-                        })
-                    }).context(concat!("Could not parse ", stringify!($name)))
+                Ok($name { address, header, entries, checksum })
             }
         }
     }
@@ -1,25 +1,102 @@
+mod decompress;
+mod diagnostics;
+mod io;
 mod macros;
 mod parsers;
 mod structs;
 mod utils;
 
-use std::io::Read;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::mem::size_of;
-use std::path::PathBuf;
-use std::{env, fs, io};
+use std::path::{Path, PathBuf};
+use std::{env, fs, iter};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use bytemuck::try_from_bytes;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
 
-use crate::parsers::parse_directories;
-use crate::structs::FirmwareEntryTable;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::parsers::{directory_tree, parse_directories};
+use crate::structs::{DirectoryNode, FirmwareEntryTable, ParsedEntry};
 use crate::utils::{find_pattern, try_find_agesa};
 
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
 #[derive(Parser, Debug)]
 #[clap(about, author, version)]
 struct Opt {
     path: PathBuf,
+
+    /// Dump every parsed PSP/BIOS entry body to this directory, alongside a
+    /// manifest listing offset, size, kind, version and architecture.
+    #[clap(long)]
+    extract: Option<PathBuf>,
+
+    /// Output format for the parsed PSP/BIOS directories.
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Debug, Serialize)]
+struct FetNode {
+    address: usize,
+    psp: DirectoryNode,
+    bios: Vec<DirectoryNode>,
+}
+
+#[derive(Debug, Serialize)]
+struct Document {
+    file: String,
+    size: u64,
+    agesa: Vec<String>,
+    fets: Vec<FetNode>,
+}
+
+/// Writes a single entry's raw body to `dir`, named by its resolved
+/// location, entry kind and version, and appends its metadata to
+/// `manifest`.
+fn extract_entry(
+    data: &[u8],
+    dir: &Path,
+    location: usize,
+    entry: ParsedEntry,
+    manifest: &mut String,
+) -> Result<()> {
+    let size = entry.size() as usize;
+    let body = data
+        .get(location..location + size)
+        .ok_or_else(|| anyhow::anyhow!("entry body out of bounds"))?;
+
+    let (kind, version, arch) = match entry {
+        ParsedEntry::Psp(e) => {
+            (e.entry.kind, e.header.get_version(), e.header.try_get_processor_arch())
+        },
+        ParsedEntry::Bhd(e) => (e.kind, entry.version(), None),
+    };
+    let file_name = format!("{:08X}_kind{:02X}_{}.bin", location, kind, version);
+
+    fs::write(dir.join(&file_name), body).context("Could not write extracted entry")?;
+
+    writeln!(
+        manifest,
+        "{}\toffset={:08X}\tsize={:08X}\tkind={:#04X}\tversion={}\tarch={}",
+        file_name,
+        location,
+        size,
+        kind,
+        version,
+        arch.unwrap_or("Unknown"),
+    )?;
+
+    Ok(())
 }
 
 fn main() -> Result<()> {
@@ -27,11 +104,21 @@ fn main() -> Result<()> {
 
     pretty_env_logger::init();
 
-    let Opt { path } = Opt::parse();
+    let Opt { path, extract, format } = Opt::parse();
+
+    if let Some(dir) = &extract {
+        fs::create_dir_all(dir).context("Could not create extraction directory")?;
+    }
 
     let file_name = path.file_name().expect("Could not get file name");
     let data = fs::read(&path).expect("Could not read file");
 
+    // The FET/AGESA scan below needs the whole image resident for pattern
+    // matching, but the directory walk after it only ever seeks to and reads
+    // one struct at a time, so it gets its own `BufReader<File>` instead of
+    // also working off `data`.
+    let mut reader = BufReader::new(File::open(&path).context("Could not open file")?);
+
     let fet_headers = find_pattern(&data, r"\xFF{16}(\xAA\x55\xAA\x55.{76})\xFF{16}");
     if fet_headers.is_empty() {
         bail!("Could not find FET header(s)!");
@@ -39,8 +126,13 @@ fn main() -> Result<()> {
 
     let agesa = try_find_agesa(&data);
 
-    log::info!(" FILE: {} ({} MB)", file_name.to_str().unwrap(), data.len() / 1024 / 1024);
-    log::info!("AGESA: {:?}", agesa);
+    if matches!(format, Format::Text) {
+        log::info!(" FILE: {} ({} MB)", file_name.to_str().unwrap(), data.len() / 1024 / 1024);
+        log::info!("AGESA: {:?}", agesa);
+    }
+
+    let mut manifest = String::new();
+    let mut fets = Vec::new();
 
     for (addr, bytes) in fet_headers {
         let bytes = match bytes.get(..size_of::<FirmwareEntryTable>()) {
@@ -59,29 +151,88 @@ fn main() -> Result<()> {
             },
         };
 
-        log::info!("");
-        log::info!("[{:08X}] FirmwareEntryTable", addr);
-
-        for (location, entry, generation) in
-            parse_directories(&data, fet.psp as usize, addr - 0x20000)
-        {
-            match entry {
-                Err(error) => log::error!("Location {:08X}, {:?}", location, error),
-                Ok(entry) => log::info!(
-                    "   Location {:08X}, Size {:08X} ({:>3} KB) // {:?} {} {}",
-                    location,
-                    entry.packed_size,
-                    entry.packed_size / 1024,
-                    generation,
-                    entry.get_version(),
-                    entry.try_get_processor_arch().unwrap_or("Unknown"),
-                ),
+        let offset = addr - 0x20000;
+
+        if matches!(format, Format::Json) {
+            fets.push(FetNode {
+                address: addr,
+                psp: directory_tree(&mut reader, fet.psp as usize, offset, None),
+                bios: fet
+                    .bios_directories()
+                    .map(|bios| directory_tree(&mut reader, bios as usize, offset, None))
+                    .collect(),
+            });
+        }
+
+        if matches!(format, Format::Text) {
+            log::info!("");
+            log::info!("[{:08X}] FirmwareEntryTable", addr);
+        }
+
+        let directories = iter::once(fet.psp).chain(fet.bios_directories());
+
+        for directory_addr in directories {
+            for (location, result, generation) in
+                parse_directories(&mut reader, directory_addr as usize, offset)
+            {
+                match result {
+                    Err(error) => {
+                        if matches!(format, Format::Text) {
+                            match error.downcast_ref::<Diagnostic>() {
+                                Some(diag) if diag.severity == Severity::Warning => {
+                                    log::warn!("\n{}", diag.render(&data, 32))
+                                },
+                                Some(diag) => log::error!("\n{}", diag.render(&data, 32)),
+                                None => log::error!("Location {:08X}, {:?}", location, error),
+                            }
+                        }
+                    },
+                    Ok(parsed_entry) => {
+                        if matches!(format, Format::Text) {
+                            log::info!(
+                                "   Location {:08X}, Size {:08X} ({:>3} KB) // {:?} {} {}",
+                                location,
+                                parsed_entry.size(),
+                                parsed_entry.size() / 1024,
+                                generation,
+                                parsed_entry.version(),
+                                parsed_entry.arch().unwrap_or("Unknown"),
+                            );
+                        }
+
+                        if let Some(dir) = &extract {
+                            if let Err(err) =
+                                extract_entry(&data, dir, location, parsed_entry, &mut manifest)
+                            {
+                                log::error!(
+                                    "Could not extract entry at {:08X}: {:?}",
+                                    location,
+                                    err
+                                );
+                            }
+                        }
+                    },
+                }
             }
         }
     }
 
+    if let Some(dir) = &extract {
+        fs::write(dir.join("manifest.txt"), manifest).context("Could not write manifest")?;
+    }
+
+    if matches!(format, Format::Json) {
+        let document = Document {
+            file: file_name.to_string_lossy().into_owned(),
+            size: data.len() as u64,
+            agesa: agesa.unwrap_or_default(),
+            fets,
+        };
+        println!("{}", serde_json::to_string_pretty(&document)?);
+    }
+
     // TODO!: less ghetto readline
-    let _ = io::stdin().read(&mut [0u8]).unwrap();
+    let _ = std::io::stdin().read(&mut [0u8]).unwrap();
 
     Ok(())
 }
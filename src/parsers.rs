@@ -1,116 +1,205 @@
-use anyhow::{anyhow, Error, Result};
-use std::iter;
+use std::io::{Read, Seek, SeekFrom};
 
-use crate::structs::{ComboDirectory, Generation, PspDirectory, PspDirectoryEntry, PspEntryHeader};
+use anyhow::{Error, Result};
+
+use crate::diagnostics::Diagnostic;
+use crate::io::FromReader;
+use crate::structs::{
+    BhdDirectory, BhdDirectoryEntry, BhdDirectoryNode, BhdEntryNode, ComboChild, ComboDirectory,
+    ComboDirectoryNode, DirectoryNode, Generation, ParsedEntry, PspDirectory, PspDirectoryEntry,
+    PspDirectoryNode, PspEntry, PspEntryHeader, PspEntryNode,
+};
 use crate::utils::resolve_location;
 
-type Iter<'a> =
-    Box<dyn Iterator<Item = (usize, Result<&'a PspEntryHeader>, Option<Generation>)> + 'a>;
+type Entries = Vec<(usize, Result<ParsedEntry>, Option<Generation>)>;
 
-fn header(location: usize, header: &PspEntryHeader, generation: Option<Generation>) -> Iter<'_> {
-    Box::new(iter::once((location, Ok(header), generation)))
+/// Seeks to `address` and reads the 4-byte directory signature that
+/// dispatches `parse_directory`/`directory_tree`, without requiring the
+/// caller to already have `address` resident in an in-memory slice.
+fn read_signature<R: Read + Seek>(reader: &mut R, address: usize) -> Result<[u8; 4]> {
+    let mut signature = [0u8; 4];
+    reader.seek(SeekFrom::Start(address as u64))?;
+    reader.read_exact(&mut signature)?;
+    Ok(signature)
 }
 
-fn error<'a>(location: usize, error: Error, generation: Option<Generation>) -> Iter<'a> {
-    Box::new(iter::once((location, Err(error), generation)))
+fn unknown_signature_error(address: usize, signature: &[u8; 4]) -> Error {
+    Diagnostic::error(
+        address,
+        format!(
+            "Unknown PSP entry signature: {} ({:#x})",
+            std::str::from_utf8(signature).unwrap_or("<invalid>"),
+            u32::from_be_bytes(*signature),
+        ),
+    )
+    .with_span(4)
+    .into()
 }
 
-fn parse_combo_directory(data: &[u8], address: usize, offset: usize) -> Iter<'_> {
-    let directory = match ComboDirectory::new(address, data) {
-        Err(err) => return error(address, err, None),
+fn parse_combo_directory<R: Read + Seek>(reader: &mut R, address: usize, offset: usize) -> Entries {
+    let directory = match ComboDirectory::new(address, reader) {
+        Err(err) => return vec![(address, Err(err), None)],
         Ok(directory) => directory,
     };
-    let entries = directory.entries.into_iter();
-    Box::new(entries.flat_map(move |e| {
-        parse_directory(data, e.location as usize, offset, Some(e.try_get_gen()))
-    }))
+    if !directory.checksum.is_valid() {
+        log::warn!(
+            "Checksum mismatch for ComboDirectory at {:08X} (expected {:08X}, computed {:08X})",
+            address,
+            directory.checksum.expected,
+            directory.checksum.computed,
+        );
+    }
+    directory
+        .entries
+        .iter()
+        .flat_map(|e| parse_directory(reader, e.location as usize, offset, Some(e.try_get_gen())))
+        .collect()
 }
 
-fn parse_psp_entry<'a>(
-    data: &'a [u8],
+fn parse_psp_entry<R: Read + Seek>(
+    reader: &mut R,
     entry: &PspDirectoryEntry,
     offset: usize,
     generation: Option<Generation>,
-) -> Iter<'a> {
+) -> Entries {
     match entry.kind {
-        0x40 | 0x70 => parse_directory(data, entry.location as usize, offset, generation),
+        0x40 | 0x70 => parse_directory(reader, entry.location as usize, offset, generation),
         0x08 | 0x12 => {
             let location = resolve_location(entry.location as usize, offset);
 
-            let data = match data.get(location..) {
-                None => {
-                    return error(location, anyhow!("Could not fetch PSP entry header"), generation)
-                },
-                Some(data) => data,
-            };
+            if reader.seek(SeekFrom::Start(location as u64)).is_err() {
+                return vec![(
+                    location,
+                    Err(Diagnostic::warning(location, "Could not fetch PSP entry header").into()),
+                    generation,
+                )];
+            }
 
-            let entry_header = match PspEntryHeader::new(data) {
-                Err(err) => return error(location, err, generation),
-                Ok(entry_header) => entry_header,
+            let header = match PspEntryHeader::from_reader(reader) {
+                Err(err) => {
+                    return vec![(
+                        location,
+                        Err(Diagnostic::warning(location, err.to_string()).into()),
+                        generation,
+                    )]
+                },
+                Ok(header) => header,
             };
 
-            header(location, entry_header, generation)
+            vec![(
+                location,
+                Ok(ParsedEntry::Psp(PspEntry { entry: *entry, header })),
+                generation,
+            )]
         },
-        _ => Box::new(iter::empty()),
+        _ => Vec::new(),
     }
 }
 
-fn parse_psp_directory(
-    data: &[u8],
+fn parse_psp_directory<R: Read + Seek>(
+    reader: &mut R,
     address: usize,
     offset: usize,
     generation: Option<Generation>,
-) -> Iter<'_> {
-    let directory = match PspDirectory::new(address, data) {
+) -> Entries {
+    let directory = match PspDirectory::new(address, reader) {
         Ok(directory) => directory,
-        Err(err) => return error(address, err, generation),
+        Err(err) => return vec![(address, Err(err), generation)],
     };
-    Box::new(
-        directory
-            .entries
-            .into_iter()
-            .flat_map(move |e| parse_psp_entry(data, e, offset, generation)),
-    )
+    if !directory.checksum.is_valid() {
+        log::warn!(
+            "Checksum mismatch for PspDirectory at {:08X} (expected {:08X}, computed {:08X})",
+            address,
+            directory.checksum.expected,
+            directory.checksum.computed,
+        );
+    }
+    directory
+        .entries
+        .iter()
+        .flat_map(|e| parse_psp_entry(reader, e, offset, generation))
+        .collect()
 }
 
-pub fn parse_directory(
-    data: &[u8],
+fn parse_bhd_entry<R: Read + Seek>(
+    reader: &mut R,
+    entry: &BhdDirectoryEntry,
+    offset: usize,
+    generation: Option<Generation>,
+) -> Entries {
+    match entry.kind {
+        0x40 | 0x70 => parse_directory(reader, entry.source as usize, offset, generation),
+        _ => {
+            let location = resolve_location(entry.source as usize, offset);
+            vec![(location, Ok(ParsedEntry::Bhd(*entry)), generation)]
+        },
+    }
+}
+
+fn parse_bhd_directory<R: Read + Seek>(
+    reader: &mut R,
     address: usize,
     offset: usize,
     generation: Option<Generation>,
-) -> Iter<'_> {
-    let address = resolve_location(address, offset);
-    match &data[address..][..4] {
-        b"2PSP" | b"2BHD" => parse_combo_directory(data, address, offset),
-        b"$PSP" | b"$PL2" => parse_psp_directory(data, address, offset, generation),
-        sig => error(
+) -> Entries {
+    let directory = match BhdDirectory::new(address, reader) {
+        Ok(directory) => directory,
+        Err(err) => return vec![(address, Err(err), generation)],
+    };
+    if !directory.checksum.is_valid() {
+        log::warn!(
+            "Checksum mismatch for BhdDirectory at {:08X} (expected {:08X}, computed {:08X})",
             address,
-            anyhow!(
-                "Unknown PSP entry signature: {} ({:#x})",
-                std::str::from_utf8(sig).unwrap_or("<invalid>"),
-                u32::from_be_bytes([sig[0], sig[1], sig[2], sig[3]]),
-            ),
-            generation,
-        ),
+            directory.checksum.expected,
+            directory.checksum.computed,
+        );
     }
+    directory
+        .entries
+        .iter()
+        .flat_map(|e| parse_bhd_entry(reader, e, offset, generation))
+        .collect()
 }
 
-pub fn parse_directories(
-    data: &[u8],
+pub fn parse_directory<R: Read + Seek>(
+    reader: &mut R,
     address: usize,
     offset: usize,
-) -> Vec<(usize, Result<&PspEntryHeader>, Option<Generation>)> {
-    let mut vec = parse_directory(data, address, offset, None).collect::<Vec<_>>();
+    generation: Option<Generation>,
+) -> Entries {
+    let address = resolve_location(address, offset);
+
+    let signature = match read_signature(reader, address) {
+        Ok(signature) => signature,
+        Err(_) => {
+            return vec![(
+                address,
+                Err(Diagnostic::error(address, "Could not fetch directory signature").into()),
+                generation,
+            )]
+        },
+    };
+
+    match &signature {
+        b"2PSP" | b"2BHD" => parse_combo_directory(reader, address, offset),
+        b"$PSP" | b"$PL2" => parse_psp_directory(reader, address, offset, generation),
+        b"$BHD" | b"$BL2" => parse_bhd_directory(reader, address, offset, generation),
+        signature => vec![(address, Err(unknown_signature_error(address, signature)), generation)],
+    }
+}
+
+pub fn parse_directories<R: Read + Seek>(reader: &mut R, address: usize, offset: usize) -> Entries {
+    let mut vec = parse_directory(reader, address, offset, None);
     vec.sort_by_key(|&(location, _, _)| location);
     vec.dedup_by_key(|&mut (location, _, _)| location);
     vec.sort_by(|(_, res1, _), (_, res2, _)| match (res1, res2) {
-        (Ok(h1), Ok(h2)) => h1.packed_size.cmp(&h2.packed_size),
+        (Ok(e1), Ok(e2)) => e1.size().cmp(&e2.size()),
         (Ok(_), Err(_)) => std::cmp::Ordering::Less,
         (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
         (Err(_), Err(_)) => std::cmp::Ordering::Equal,
     });
     vec.sort_by(|(_, res1, _), (_, res2, _)| match (res1, res2) {
-        (Ok(h1), Ok(h2)) => h1.get_version().cmp(&h2.get_version()),
+        (Ok(e1), Ok(e2)) => e1.version().cmp(&e2.version()),
         (Ok(_), Err(_)) => std::cmp::Ordering::Less,
         (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
         (Err(_), Err(_)) => std::cmp::Ordering::Equal,
@@ -121,3 +210,153 @@ pub fn parse_directories(
     // });
     vec
 }
+
+/// Same traversal as `parse_directory`, but builds the nested combo →
+/// directory → entry tree instead of flattening it into a sorted vector, for
+/// `--format json` consumers that care which combo `Generation` an entry
+/// came from.
+pub fn directory_tree<R: Read + Seek>(
+    reader: &mut R,
+    address: usize,
+    offset: usize,
+    generation: Option<Generation>,
+) -> DirectoryNode {
+    let address = resolve_location(address, offset);
+
+    let signature = match read_signature(reader, address) {
+        Ok(signature) => signature,
+        Err(err) => return DirectoryNode::Error { address, message: err.to_string() },
+    };
+
+    match &signature {
+        b"2PSP" | b"2BHD" => combo_directory_tree(reader, address, offset),
+        b"$PSP" | b"$PL2" => psp_directory_tree(reader, address, offset, generation),
+        b"$BHD" | b"$BL2" => bhd_directory_tree(reader, address, offset, generation),
+        signature => {
+            DirectoryNode::Error { address, message: unknown_signature_error(address, signature).to_string() }
+        },
+    }
+}
+
+fn combo_directory_tree<R: Read + Seek>(reader: &mut R, address: usize, offset: usize) -> DirectoryNode {
+    let directory = match ComboDirectory::new(address, reader) {
+        Err(err) => return DirectoryNode::Error { address, message: err.to_string() },
+        Ok(directory) => directory,
+    };
+
+    let children = directory
+        .entries
+        .iter()
+        .map(|e| {
+            let generation = e.try_get_gen();
+            ComboChild {
+                generation,
+                directory: directory_tree(reader, e.location as usize, offset, Some(generation)),
+            }
+        })
+        .collect();
+
+    DirectoryNode::Combo(ComboDirectoryNode { address, checksum: directory.checksum, children })
+}
+
+fn psp_directory_tree<R: Read + Seek>(
+    reader: &mut R,
+    address: usize,
+    offset: usize,
+    generation: Option<Generation>,
+) -> DirectoryNode {
+    let directory = match PspDirectory::new(address, reader) {
+        Err(err) => return DirectoryNode::Error { address, message: err.to_string() },
+        Ok(directory) => directory,
+    };
+
+    let entries = directory
+        .entries
+        .iter()
+        .map(|e| psp_entry_tree(reader, e, offset, generation))
+        .collect();
+
+    DirectoryNode::Psp(PspDirectoryNode { address, checksum: directory.checksum, entries })
+}
+
+fn psp_entry_tree<R: Read + Seek>(
+    reader: &mut R,
+    entry: &PspDirectoryEntry,
+    offset: usize,
+    generation: Option<Generation>,
+) -> PspEntryNode {
+    match entry.kind {
+        0x40 | 0x70 => {
+            PspEntryNode::Directory(directory_tree(reader, entry.location as usize, offset, generation))
+        },
+        0x08 | 0x12 => {
+            let location = resolve_location(entry.location as usize, offset);
+
+            let result = reader
+                .seek(SeekFrom::Start(location as u64))
+                .map_err(Error::from)
+                .and_then(|_| PspEntryHeader::from_reader(reader));
+
+            match result {
+                Ok(header) => PspEntryNode::Entry {
+                    location,
+                    kind: entry.kind,
+                    sub_program: entry.sub_program,
+                    rom_id: entry.rom_id,
+                    size: entry.size,
+                    packed_size: header.packed_size,
+                    version: header.get_version(),
+                    arch: header.try_get_processor_arch(),
+                },
+                Err(err) => PspEntryNode::Error { location, message: err.to_string() },
+            }
+        },
+        _ => PspEntryNode::Error {
+            location: resolve_location(entry.location as usize, offset),
+            message: format!("Unhandled entry kind {:#04X}", entry.kind),
+        },
+    }
+}
+
+fn bhd_directory_tree<R: Read + Seek>(
+    reader: &mut R,
+    address: usize,
+    offset: usize,
+    generation: Option<Generation>,
+) -> DirectoryNode {
+    let directory = match BhdDirectory::new(address, reader) {
+        Err(err) => return DirectoryNode::Error { address, message: err.to_string() },
+        Ok(directory) => directory,
+    };
+
+    let entries = directory
+        .entries
+        .iter()
+        .map(|e| bhd_entry_tree(reader, e, offset, generation))
+        .collect();
+
+    DirectoryNode::Bhd(BhdDirectoryNode { address, checksum: directory.checksum, entries })
+}
+
+fn bhd_entry_tree<R: Read + Seek>(
+    reader: &mut R,
+    entry: &BhdDirectoryEntry,
+    offset: usize,
+    generation: Option<Generation>,
+) -> BhdEntryNode {
+    match entry.kind {
+        0x40 | 0x70 => {
+            BhdEntryNode::Directory(Box::new(directory_tree(reader, entry.source as usize, offset, generation)))
+        },
+        _ => BhdEntryNode::Entry {
+            location: resolve_location(entry.source as usize, offset),
+            kind: entry.kind,
+            region_kind: entry.region_kind,
+            reset_image: entry.reset_image(),
+            copy_image: entry.copy_image(),
+            size: entry.size,
+            source: entry.source,
+            destination: entry.destination,
+        },
+    }
+}
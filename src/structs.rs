@@ -2,13 +2,33 @@ use core::fmt;
 use std::cmp::Ordering;
 use std::mem::size_of;
 
-use anyhow::{bail, Context, Result};
-use bytemuck::{try_from_bytes, Pod, Zeroable};
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use serde::Serialize;
 use static_assertions::assert_eq_size;
 
 use crate::make_dir;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Pod, Zeroable)]
+/// Which combo-directory chip generation a `ComboDirectoryEntry` selects.
+/// `id_select == 0` means `id` is a PSP/BIOS directory id, `id_select == 1`
+/// means `id` is a chip family id.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum Generation {
+    Id(u32),
+    Family(u32),
+}
+
+impl fmt::Display for Generation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Generation::Id(id) => write!(f, "id={:#x}", id),
+            Generation::Family(id) => write!(f, "family={:#x}", id),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Pod, Zeroable)]
 #[repr(C)]
 pub struct Version {
     pub build: u8,
@@ -57,12 +77,41 @@ impl fmt::UpperHex for Version {
     }
 }
 
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[derive(Debug, Copy, Clone, Serialize, Pod, Zeroable)]
 #[repr(C)]
 pub struct FirmwareEntryTable {
     pub signature: [u8; 0x04],
+    #[serde(skip)]
     pub rsv_04: [u8; 0x10],
     pub psp: u32,
+    pub bios0: u32,
+    pub bios1: u32,
+    pub bios2: u32,
+    pub bios3: u32,
+}
+
+impl FirmwareEntryTable {
+    /// The BIOS directory table pointers for the (up to four) BIOS ROM
+    /// straps, skipping unused slots (`0` or `0xFFFFFFFF`).
+    pub fn bios_directories(&self) -> impl Iterator<Item = u32> {
+        [self.bios0, self.bios1, self.bios2, self.bios3]
+            .into_iter()
+            .filter(|&addr| addr != 0 && addr != u32::MAX)
+    }
+}
+
+/// Result of verifying a directory's Fletcher-32 checksum: the value stored
+/// in the header versus the one computed over the directory bytes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+pub struct Checksum {
+    pub expected: u32,
+    pub computed: u32,
+}
+
+impl Checksum {
+    pub fn is_valid(&self) -> bool {
+        self.expected == self.computed
+    }
 }
 
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
@@ -84,12 +133,13 @@ pub struct ComboDirectoryHeader {
     pub rsvd_10: [u8; 0x10],
 }
 
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[derive(Debug, Copy, Clone, Serialize, Pod, Zeroable)]
 #[repr(C)]
 pub struct PspDirectoryEntry {
     pub kind: u8,
     pub sub_program: u8,
     pub rom_id: u8,
+    #[serde(skip)]
     pub rsvd_03: u8,
     pub size: u32,
     pub location: u64,
@@ -103,28 +153,66 @@ pub struct ComboDirectoryEntry {
     pub location: u64,
 }
 
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+impl ComboDirectoryEntry {
+    pub fn try_get_gen(&self) -> Generation {
+        if self.id_select == 0 {
+            Generation::Id(self.id)
+        } else {
+            Generation::Family(self.id)
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Pod, Zeroable)]
+#[repr(C)]
+pub struct BhdDirectoryEntry {
+    pub kind: u8,
+    pub region_kind: u8,
+    /// Bit 0 `reset_image`, bit 1 `copy_image`, bit 2 `read_only`, bit 3
+    /// `compressed`, bits 4-7 `instance`.
+    pub flags: u8,
+    #[serde(skip)]
+    pub rsvd_03: u8,
+    pub size: u32,
+    pub source: u64,
+    pub destination: u64,
+}
+
+impl BhdDirectoryEntry {
+    pub fn reset_image(&self) -> bool {
+        self.flags & 0b0001 != 0
+    }
+    pub fn copy_image(&self) -> bool {
+        self.flags & 0b0010 != 0
+    }
+    pub fn read_only(&self) -> bool {
+        self.flags & 0b0100 != 0
+    }
+    pub fn compressed(&self) -> bool {
+        self.flags & 0b1000 != 0
+    }
+    pub fn instance(&self) -> u8 {
+        self.flags >> 4
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Pod, Zeroable)]
 #[repr(C)]
 pub struct PspEntryHeader {
+    #[serde(skip)]
     pub rsvd_0: [u8; 0x10],
     pub signature: [u8; 0x4],
+    #[serde(skip)]
     pub rsvd_14: [u8; 0x4c],
     pub version: Version,
+    #[serde(skip)]
     pub rsvd_64: [u8; 0x8],
     pub packed_size: u32,
+    #[serde(skip)]
     pub rsvd_70: [u8; 0x90],
 }
 
 impl PspEntryHeader {
-    pub fn new(data: &[u8]) -> Result<&PspEntryHeader> {
-        let data = match data.get(..size_of::<Self>()) {
-            None => bail!("Could not fetch PSP entry header"),
-            Some(data) => data,
-        };
-
-        try_from_bytes::<PspEntryHeader>(data).context("Could not parse PSP entry header")
-    }
-
     pub fn get_version(&self) -> Version {
         if self.version.is_zero() {
             Version {
@@ -174,14 +262,15 @@ pub struct EfiGuidDefinedSection {
 }
 
 impl EfiGuidDefinedSection {
-    pub fn new(data: &[u8]) -> Result<&EfiGuidDefinedSection> {
-        let data = match data.get(..size_of::<Self>()) {
-            None => bail!("Could not fetch guid defined section header"),
-            Some(data) => data,
-        };
+    /// Parses the header from `data` via `FromReader`, wrapping it in a
+    /// `Cursor` since callers (pattern matches over an in-memory scan) only
+    /// have a byte slice, not a seekable stream, to read it from.
+    pub fn new(data: &[u8]) -> Result<EfiGuidDefinedSection> {
+        use std::io::Cursor;
 
-        try_from_bytes::<EfiGuidDefinedSection>(data)
-            .context("Could not parse guid defined section header")
+        use crate::io::FromReader;
+
+        EfiGuidDefinedSection::from_reader(&mut Cursor::new(data))
     }
     pub fn get_full_size(&self) -> usize {
         (self.size[0] as u32 | (self.size[1] as u32) << 8 | (self.size[2] as u32) << 16) as usize
@@ -191,8 +280,128 @@ impl EfiGuidDefinedSection {
     }
 }
 
+/// A parsed PSP/BIOS entry body header together with the directory entry
+/// that pointed at it, so callers have the byte range (`entry.location` +
+/// `header.packed_size`) alongside the decoded metadata.
+#[derive(Debug, Copy, Clone)]
+pub struct PspEntry {
+    pub entry: PspDirectoryEntry,
+    pub header: PspEntryHeader,
+}
+
+/// A leaf entry from either a PSP or a BIOS ($BHD/$BL2) directory.
+#[derive(Debug, Copy, Clone)]
+pub enum ParsedEntry {
+    Psp(PspEntry),
+    Bhd(BhdDirectoryEntry),
+}
+
+impl ParsedEntry {
+    pub fn size(&self) -> u32 {
+        match self {
+            ParsedEntry::Psp(e) => e.header.packed_size,
+            ParsedEntry::Bhd(e) => e.size,
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        match self {
+            ParsedEntry::Psp(e) => e.header.get_version(),
+            ParsedEntry::Bhd(_) => Version { build: 0, micro: 0, minor: 0, major: 0 },
+        }
+    }
+
+    pub fn arch(&self) -> Option<&'static str> {
+        match self {
+            ParsedEntry::Psp(e) => e.header.try_get_processor_arch(),
+            ParsedEntry::Bhd(_) => None,
+        }
+    }
+}
+
+/// A node in the combo → directory → entry parse tree, as consumed by the
+/// `--format json` output. Unlike `parse_directories`'s flattened, sorted
+/// vector, this preserves the nesting so a consumer can see which combo
+/// `Generation` each entry came from.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DirectoryNode {
+    Combo(ComboDirectoryNode),
+    Psp(PspDirectoryNode),
+    Bhd(BhdDirectoryNode),
+    Error { address: usize, message: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComboDirectoryNode {
+    pub address: usize,
+    pub checksum: Checksum,
+    pub children: Vec<ComboChild>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComboChild {
+    pub generation: Generation,
+    pub directory: DirectoryNode,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PspDirectoryNode {
+    pub address: usize,
+    pub checksum: Checksum,
+    pub entries: Vec<PspEntryNode>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PspEntryNode {
+    Directory(DirectoryNode),
+    Entry {
+        location: usize,
+        kind: u8,
+        sub_program: u8,
+        rom_id: u8,
+        size: u32,
+        packed_size: u32,
+        version: Version,
+        arch: Option<&'static str>,
+    },
+    Error {
+        location: usize,
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct BhdDirectoryNode {
+    pub address: usize,
+    pub checksum: Checksum,
+    pub entries: Vec<BhdEntryNode>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BhdEntryNode {
+    Directory(Box<DirectoryNode>),
+    Entry {
+        location: usize,
+        kind: u8,
+        region_kind: u8,
+        reset_image: bool,
+        copy_image: bool,
+        size: u32,
+        source: u64,
+        destination: u64,
+    },
+    Error {
+        location: usize,
+        message: String,
+    },
+}
+
 make_dir!(pub ComboDirectory, ComboDirectoryHeader, ComboDirectoryEntry);
 make_dir!(pub PspDirectory, DirectoryHeader, PspDirectoryEntry);
+make_dir!(pub BhdDirectory, DirectoryHeader, BhdDirectoryEntry);
 
 assert_eq_size!([u8; 0x10], PspDirectoryEntry);
 assert_eq_size!([u8; 0x10], DirectoryHeader);
@@ -200,3 +409,4 @@ assert_eq_size!([u8; 0x20], ComboDirectoryHeader);
 assert_eq_size!([u8; 0x10], ComboDirectoryEntry);
 assert_eq_size!([u8; 0x100], PspEntryHeader);
 assert_eq_size!([u8; 0x18], EfiGuidDefinedSection);
+assert_eq_size!([u8; 0x18], BhdDirectoryEntry);
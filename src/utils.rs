@@ -1,13 +1,16 @@
 use std::mem::size_of;
 
 use anyhow::{bail, Result};
-use lzma_rs::lzma_decompress;
 use regex::bytes::Regex;
 
+use crate::decompress::decompress_section;
 use crate::structs::EfiGuidDefinedSection;
 
 const AGESA_PATTERN: &str = r"(AGESA![0-9a-zA-Z]{0,10}\x00{0,1}[0-9a-zA-Z .\-]+)";
-const AGESA_SECTION_PATTERN: &str = r"\x93\xFD\x21\x9E\x72\x9C\x15\x4C\x8C\x4B\xE7\x7F\x1D\xB2\xD7\x92.{8}(.{4}\x98\x58\x4E\xEE\x14\x39\x59\x42\x9D\x6E\xDC\x7B\xD7\x94\x03\xCF.{4})";
+// `.{4}` = size+type of the common section header, `.{16}` = the section's
+// GUID (dispatched by `decompress_section`), `.{4}` = DataOffset+Attributes.
+const AGESA_SECTION_PATTERN: &str =
+    r"\x93\xFD\x21\x9E\x72\x9C\x15\x4C\x8C\x4B\xE7\x7F\x1D\xB2\xD7\x92.{8}(.{4}.{16}.{4})";
 
 pub fn find_pattern<'a>(data: &'a [u8], pattern: &str) -> Vec<(usize, &'a [u8])> {
     let regex_string = &["(?s-u)", pattern].concat();
@@ -24,6 +27,28 @@ pub fn resolve_location(location: usize, offset: usize) -> usize {
     (location & 0x00FFFFFF) + offset
 }
 
+/// Fletcher-32 over `data`, treated as little-endian 16-bit words (a trailing
+/// odd byte is zero-padded). This is the checksum AMD PSP/BIOS directories
+/// use to protect themselves.
+pub fn fletcher32(data: &[u8]) -> u32 {
+    let mut sum0: u32 = 0;
+    let mut sum1: u32 = 0;
+
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        let word = u16::from_le_bytes([chunk[0], chunk[1]]) as u32;
+        sum0 = (sum0 + word) % 65535;
+        sum1 = (sum1 + sum0) % 65535;
+    }
+    if let [last] = chunks.remainder() {
+        let word = u16::from_le_bytes([*last, 0]) as u32;
+        sum0 = (sum0 + word) % 65535;
+        sum1 = (sum1 + sum0) % 65535;
+    }
+
+    (sum1 << 16) | sum0
+}
+
 pub fn try_find_agesa(data: &[u8]) -> Result<Vec<String>> {
     let agesa = find_pattern(&data, AGESA_PATTERN)
         .into_iter()
@@ -49,7 +74,7 @@ pub fn try_find_agesa(data: &[u8]) -> Result<Vec<String>> {
             },
         };
 
-        let mut enc_body = match data
+        let enc_body = match data
             .get(addr + size_of::<EfiGuidDefinedSection>()..)
             .and_then(|x| x.get(..guid_section_header.get_body_size()))
         {
@@ -60,12 +85,17 @@ pub fn try_find_agesa(data: &[u8]) -> Result<Vec<String>> {
             },
         };
 
-        let mut dec_body: Vec<u8> = Vec::new();
-
-        if lzma_decompress(&mut enc_body, &mut dec_body).is_err() {
-            log::error!("Could not decompress section at {:08X}", addr);
-            continue;
-        }
+        let dec_body = match decompress_section(&guid_section_header.guid, enc_body) {
+            Ok(Some(dec_body)) => dec_body,
+            Ok(None) => {
+                log::warn!("Unknown GUID-defined section codec at {:08X}, skipping", addr);
+                continue;
+            },
+            Err(err) => {
+                log::error!("Could not decompress section at {:08X} ({:?})", addr, err);
+                continue;
+            },
+        };
 
         match find_pattern(&dec_body, AGESA_PATTERN).first().map(|(_, x)| {
             x.iter().map(|&x| if x == 0 { ' ' } else { x as char }).collect::<String>()